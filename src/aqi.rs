@@ -0,0 +1,132 @@
+//! EPA AQI breakpoint math. See
+//! https://en.wikipedia.org/wiki/Air_quality_index#United_States
+
+use std::collections::VecDeque;
+
+const PM02: [f64; 7] = [0.0, 9.0, 35.4, 55.4, 125.4, 225.4, 325.4];
+const PM10: [f64; 7] = [0.0, 54.0, 154.0, 254.0, 354.0, 424.0, 604.0];
+
+fn compute_one_aqi(datum: f64, vector: [f64; 7]) -> u32 {
+    const AQI: [f64; 7] = [0.0, 50.0, 100.0, 150.0, 200.0, 300.0, 500.0];
+    let mut i = 0;
+    while i <= 6 && datum >= vector[i] {
+        i = i + 1;
+    }
+    return (((AQI[i] - AQI[i-1]) /
+    (vector[i] - vector[i-1])) * (datum - vector[i-1]) + AQI[i-1]) as u32;
+}
+
+pub fn compute_aqi(pm02: f64, pm10: f64) -> u32 {
+    use std::cmp;
+    let mut v: u32 = 0;
+    v = cmp::max(v, compute_one_aqi(pm02, PM02));
+    v = cmp::max(v, compute_one_aqi(pm10, PM10));
+    return v;
+}
+
+const NOWCAST_HOURS: usize = 12;
+// EPA NowCast requires at least 2 of the 3 most recent hours to have data.
+const NOWCAST_MIN_RECENT_HOURS: usize = 2;
+const NOWCAST_RECENT_WINDOW: usize = 3;
+const NOWCAST_MIN_WEIGHT: f64 = 0.5;
+
+/// Rolling per-sensor hourly history used to compute an EPA NowCast
+/// concentration instead of feeding an instantaneous reading straight into
+/// the breakpoint tables. `hours[0]` is always the current hour.
+/// See https://www.airnow.gov/aqi/aqi-basics/aqi-calculator-concentration/
+pub struct NowCast {
+    hours: VecDeque<Option<f64>>,
+    hour_epoch: i64,
+}
+
+impl NowCast {
+    pub fn new() -> Self {
+        Self {
+            hours: VecDeque::from(vec![None; NOWCAST_HOURS]),
+            hour_epoch: current_hour_epoch(),
+        }
+    }
+
+    /// Records a fresh instantaneous reading, rolling the history forward by
+    /// however many hour boundaries have passed since the last update.
+    pub fn record(&mut self, value: f64) {
+        let now_hour = current_hour_epoch();
+        let elapsed_hours = (now_hour - self.hour_epoch).max(0) as usize;
+        if elapsed_hours >= NOWCAST_HOURS {
+            self.hours.iter_mut().for_each(|hour| *hour = None);
+        } else {
+            for _ in 0..elapsed_hours {
+                self.hours.pop_back();
+                self.hours.push_front(None);
+            }
+        }
+        self.hour_epoch = now_hour;
+        self.hours[0] = Some(value);
+    }
+
+    /// Computes the NowCast-weighted concentration, falling back to the raw
+    /// `current` reading if fewer than `NOWCAST_MIN_RECENT_HOURS` of the
+    /// last `NOWCAST_RECENT_WINDOW` hours have data.
+    pub fn value(&self, current: f64) -> f64 {
+        let recent_present = self.hours.iter()
+            .take(NOWCAST_RECENT_WINDOW)
+            .filter(|hour| hour.is_some())
+            .count();
+        if recent_present < NOWCAST_MIN_RECENT_HOURS {
+            return current;
+        }
+        let present: Vec<(i32, f64)> = self.hours.iter().enumerate()
+            .filter_map(|(i, hour)| hour.map(|c| (i as i32, c)))
+            .collect();
+        let c_min = present.iter().map(|(_, c)| *c).fold(f64::INFINITY, f64::min);
+        let c_max = present.iter().map(|(_, c)| *c).fold(f64::NEG_INFINITY, f64::max);
+        let w = if c_max > 0.0 { (c_min / c_max).max(NOWCAST_MIN_WEIGHT) } else { NOWCAST_MIN_WEIGHT };
+        let (weighted_sum, weight_sum) = present.iter().fold((0.0, 0.0), |(num, den), (i, c)| {
+            let weight = w.powi(*i);
+            (num + weight * c, den + weight)
+        });
+        if weight_sum == 0.0 { current } else { weighted_sum / weight_sum }
+    }
+}
+
+fn current_hour_epoch() -> i64 {
+    chrono::Utc::now().timestamp() / 3600
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_current_with_too_little_recent_history() {
+        let mut hours = VecDeque::from(vec![None; NOWCAST_HOURS]);
+        hours[0] = Some(10.0);
+        let nowcast = NowCast { hours, hour_epoch: 0 };
+        // Only 1 of the last 3 hours has data; EPA NowCast requires 2.
+        assert_eq!(nowcast.value(10.0), 10.0);
+    }
+
+    #[test]
+    fn weights_recent_hours_more_when_concentration_is_stable() {
+        let mut hours = VecDeque::from(vec![None; NOWCAST_HOURS]);
+        hours[0] = Some(10.0);
+        hours[1] = Some(10.0);
+        let nowcast = NowCast { hours, hour_epoch: 0 };
+        // With equal concentrations, c_min/c_max is 1.0, so both hours carry
+        // equal weight and the NowCast value is just their average.
+        assert_eq!(nowcast.value(10.0), 10.0);
+    }
+
+    #[test]
+    fn weights_older_hours_less_when_concentration_is_volatile() {
+        let mut hours = VecDeque::from(vec![None; NOWCAST_HOURS]);
+        hours[0] = Some(20.0);
+        hours[1] = Some(10.0);
+        let nowcast = NowCast { hours, hour_epoch: 0 };
+        // c_min/c_max = 0.5, so the weight ratio between hour 0 and hour 1 is
+        // w^0 : w^1 = 1 : 0.5 -- the older, lower reading counts for a third
+        // of the total weight, pulling the result toward the newer reading.
+        let expected = (20.0 + 0.5 * 10.0) / (1.0 + 0.5);
+        assert_eq!(nowcast.value(20.0), expected);
+    }
+}
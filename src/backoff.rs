@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/// Exponential backoff with jitter, so a failing HTTP endpoint or InfluxDB
+/// connection isn't hammered at the same fixed rate as a healthy one.
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self { base, max, failures: 0 }
+    }
+
+    /// Delay to sleep before the next retry; each call counts as a failure
+    /// until `reset` is called.
+    pub fn next_delay(&mut self) -> Duration {
+        let exp = self.base.saturating_mul(1u32 << self.failures.min(20));
+        let delay = exp.min(self.max);
+        self.failures += 1;
+        let jitter_max_ms = ((delay.as_millis() as u64) / 4).max(1);
+        let jitter = Duration::from_millis(rand::thread_rng().gen_range(0..=jitter_max_ms));
+        delay + jitter
+    }
+
+    /// Call after a success to clear the failure count.
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamps_to_max_after_enough_failures() {
+        let base = Duration::from_secs(1);
+        let max = Duration::from_secs(4);
+        let mut backoff = Backoff::new(base, max);
+        // Exponential growth (1s, 2s, 4s, ...) hits the cap after a few
+        // calls; keep going past that point before asserting it stays there.
+        for _ in 0..5 {
+            backoff.next_delay();
+        }
+        for _ in 0..5 {
+            let delay = backoff.next_delay();
+            assert!(delay >= max, "delay {delay:?} should be at least max {max:?}");
+            assert!(delay <= max + max / 4, "delay {delay:?} should not exceed max + jitter");
+        }
+    }
+
+    #[test]
+    fn reset_restarts_growth_from_base() {
+        let base = Duration::from_secs(1);
+        let max = Duration::from_secs(300);
+        let mut backoff = Backoff::new(base, max);
+        backoff.next_delay();
+        backoff.next_delay();
+        backoff.reset();
+        let delay = backoff.next_delay();
+        assert!(delay >= base, "delay {delay:?} should be at least base {base:?}");
+        assert!(delay <= base + base / 4, "delay {delay:?} should not exceed base + jitter after reset");
+    }
+}
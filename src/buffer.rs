@@ -0,0 +1,277 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use crate::source::{DataPoint, FieldValue};
+
+type Result<T> = core::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// A queue of points that failed to write to InfluxDB, so they can be
+/// replayed once the connection recovers instead of being lost. Whether it
+/// survives a restart depends on the implementor.
+pub trait PointBuffer: Send {
+    fn push(&mut self, point: DataPoint);
+    fn drain_batch(&mut self, n: usize) -> Vec<DataPoint>;
+    /// Puts a previously-drained batch back at the front of the queue, in
+    /// its original order, so a failed flush doesn't reorder points behind
+    /// ones that were never drained (breaking FIFO replay).
+    fn requeue_front(&mut self, points: Vec<DataPoint>);
+    fn len(&self) -> usize;
+}
+
+/// In-memory buffer. Simple, but empties on restart.
+pub struct MemoryBuffer {
+    points: VecDeque<DataPoint>,
+    max_len: usize,
+}
+
+impl MemoryBuffer {
+    pub fn new(max_len: usize) -> Self {
+        Self { points: VecDeque::new(), max_len }
+    }
+}
+
+impl PointBuffer for MemoryBuffer {
+    fn push(&mut self, point: DataPoint) {
+        if self.points.len() >= self.max_len {
+            self.points.pop_front();
+        }
+        self.points.push_back(point);
+    }
+
+    fn drain_batch(&mut self, n: usize) -> Vec<DataPoint> {
+        let n = n.min(self.points.len());
+        self.points.drain(..n).collect()
+    }
+
+    fn requeue_front(&mut self, points: Vec<DataPoint>) {
+        for point in points.into_iter().rev() {
+            self.points.push_front(point);
+        }
+        while self.points.len() > self.max_len {
+            self.points.pop_back();
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+}
+
+/// Buffer that mirrors its queue to a line-protocol file on disk, so
+/// unwritten points survive a process restart, not just an Influx outage.
+pub struct FileBuffer {
+    path: PathBuf,
+    points: VecDeque<DataPoint>,
+    max_len: usize,
+}
+
+impl FileBuffer {
+    pub fn new(path: PathBuf, max_len: usize) -> Result<Self> {
+        let points = if path.exists() {
+            BufReader::new(File::open(&path)?)
+                .lines()
+                .map_while(|line| line.ok())
+                .filter_map(|line| parse_line(&line))
+                .collect()
+        } else {
+            VecDeque::new()
+        };
+        Ok(Self { path, points, max_len })
+    }
+
+    fn persist(&self) {
+        let result = File::create(&self.path).and_then(|mut file| {
+            for point in &self.points {
+                writeln!(file, "{}", format_line(point))?;
+            }
+            Ok(())
+        });
+        if let Err(error) = result {
+            println!("Error persisting point buffer to {:?}: {error:?}", self.path);
+        }
+    }
+}
+
+impl PointBuffer for FileBuffer {
+    fn push(&mut self, point: DataPoint) {
+        if self.points.len() >= self.max_len {
+            self.points.pop_front();
+        }
+        self.points.push_back(point);
+        self.persist();
+    }
+
+    fn drain_batch(&mut self, n: usize) -> Vec<DataPoint> {
+        let n = n.min(self.points.len());
+        let drained: Vec<DataPoint> = self.points.drain(..n).collect();
+        if !drained.is_empty() {
+            self.persist();
+        }
+        drained
+    }
+
+    fn requeue_front(&mut self, points: Vec<DataPoint>) {
+        if points.is_empty() {
+            return;
+        }
+        for point in points.into_iter().rev() {
+            self.points.push_front(point);
+        }
+        while self.points.len() > self.max_len {
+            self.points.pop_back();
+        }
+        self.persist();
+    }
+
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace(',', "\\,").replace(' ', "\\ ").replace('=', "\\=")
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("\\=", "=").replace("\\ ", " ").replace("\\,", ",").replace("\\\\", "\\")
+}
+
+fn format_line(point: &DataPoint) -> String {
+    let mut series = escape(&point.measurement);
+    for (key, val) in &point.tags {
+        series.push(',');
+        series.push_str(&escape(key));
+        series.push('=');
+        series.push_str(&escape(val));
+    }
+    let fields = point.fields.iter().map(|(key, value)| {
+        let value = match value {
+            FieldValue::Int(v) => format!("{v}i"),
+            FieldValue::Float(v) => format!("{v}"),
+        };
+        format!("{}={}", escape(key), value)
+    }).collect::<Vec<_>>().join(",");
+    format!("{series} {fields} {}", point.timestamp)
+}
+
+/// Splits `s` on unescaped occurrences of `delim`, matching how `escape`
+/// backslash-prefixes that same set of characters. A `\` always escapes the
+/// character after it, so a split never lands inside an escape sequence.
+fn split_unescaped(s: &str, delim: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+    for c in s.chars() {
+        if escaped {
+            current.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            current.push(c);
+            escaped = true;
+        } else if c == delim {
+            parts.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    parts.push(current);
+    parts
+}
+
+/// Like `split_unescaped`, but only splits on the first unescaped `delim`.
+fn split_unescaped_once(s: &str, delim: char) -> Option<(String, String)> {
+    let mut key = String::new();
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            key.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            key.push(c);
+            escaped = true;
+        } else if c == delim {
+            return Some((key, s[i + c.len_utf8()..].to_string()));
+        } else {
+            key.push(c);
+        }
+    }
+    None
+}
+
+fn parse_line(line: &str) -> Option<DataPoint> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    let mut parts = split_unescaped(line, ' ').into_iter();
+    let series = parts.next()?;
+    let fields_str = parts.next()?;
+    let timestamp: i64 = parts.next()?.parse().ok()?;
+
+    let mut series_parts = split_unescaped(&series, ',').into_iter();
+    let measurement = unescape(&series_parts.next()?);
+    let tags = series_parts.filter_map(|kv| {
+        let (key, val) = split_unescaped_once(&kv, '=')?;
+        Some((unescape(&key), unescape(&val)))
+    }).collect();
+
+    let fields = split_unescaped(&fields_str, ',').into_iter().filter_map(|kv| {
+        let (key, raw) = split_unescaped_once(&kv, '=')?;
+        let key = unescape(&key);
+        let value = match raw.strip_suffix('i') {
+            Some(int_part) => FieldValue::Int(int_part.parse().ok()?),
+            None => FieldValue::Float(raw.parse().ok()?),
+        };
+        Some((key, value))
+    }).collect();
+
+    Some(DataPoint { measurement, tags, fields, timestamp })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_a_point_with_a_spaced_tag_value() {
+        let point = DataPoint {
+            measurement: "airgradient".to_string(),
+            timestamp: 1_700_000_000_000_000_000,
+            tags: vec![
+                ("location".to_string(), "Living Room".to_string()),
+                ("serialno".to_string(), "abc123".to_string()),
+            ],
+            fields: vec![
+                ("pm02".to_string(), FieldValue::Int(12)),
+                ("temp".to_string(), FieldValue::Float(21.5)),
+            ],
+        };
+        let line = format_line(&point);
+        let parsed = parse_line(&line).expect("line should parse");
+        assert_eq!(parsed.measurement, point.measurement);
+        assert_eq!(parsed.timestamp, point.timestamp);
+        assert_eq!(parsed.tags, point.tags);
+        for ((key, value), (expected_key, expected_value)) in parsed.fields.iter().zip(point.fields.iter()) {
+            assert_eq!(key, expected_key);
+            match (value, expected_value) {
+                (FieldValue::Int(a), FieldValue::Int(b)) => assert_eq!(a, b),
+                (FieldValue::Float(a), FieldValue::Float(b)) => assert_eq!(a, b),
+                _ => panic!("field kind mismatch for {key}"),
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_values_containing_commas_and_backslashes() {
+        let point = DataPoint {
+            measurement: "airgradient".to_string(),
+            timestamp: 42,
+            tags: vec![("note".to_string(), r"a, b\c".to_string())],
+            fields: vec![("pm02".to_string(), FieldValue::Int(1))],
+        };
+        let parsed = parse_line(&format_line(&point)).expect("line should parse");
+        assert_eq!(parsed.tags, point.tags);
+    }
+}
@@ -0,0 +1,194 @@
+use serde::Deserialize;
+
+use crate::buffer::{FileBuffer, MemoryBuffer, PointBuffer};
+use crate::source::{DataPoint, FieldValue};
+
+type Result<T> = core::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+//***************************** Influx **********************************
+
+// This is a workaround.
+// ideally we'd just use a BTreeMap<String,String>, but
+// the config crate case squashes keys. Doing it this way
+// makes the tag a value, rather than a key.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SettingsPair {
+    key: String,
+    val: String,
+}
+
+fn default_max_buffered_points() -> usize { 10_000 }
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct InfluxSettings {
+    token: String,
+    bucket: String,
+    org: String,
+    url: String,
+    tags: Vec<SettingsPair>,
+    /// If set, failed writes are buffered to this file so they survive a
+    /// restart; otherwise they're buffered in memory only.
+    #[serde(default)]
+    buffer_path: Option<String>,
+    /// Oldest buffered points are dropped once this many are queued.
+    #[serde(default = "default_max_buffered_points")]
+    max_buffered_points: usize,
+}
+
+/// Points per batch when replaying the retry buffer after a reconnect.
+const FLUSH_BATCH_SIZE: usize = 500;
+
+pub struct Influx {
+    cfg: InfluxSettings,
+    client: Option<influxdb2::Client>,
+    buffer: Box<dyn PointBuffer>,
+    hostname: String,
+}
+
+impl Influx {
+    pub fn new(cfg: &InfluxSettings) -> Result<Self> {
+        let buffer: Box<dyn PointBuffer> = match &cfg.buffer_path {
+            Some(path) => Box::new(FileBuffer::new(path.into(), cfg.max_buffered_points)?),
+            None => Box::new(MemoryBuffer::new(cfg.max_buffered_points)),
+        };
+        let hostname = gethostname::gethostname().to_string_lossy().into_owned();
+        let mut cfg = cfg.clone();
+        // Default to tagging points with the machine's hostname so a fleet
+        // of monitors stays distinguishable without editing every TOML.
+        if !cfg.tags.iter().any(|tag| tag.key == "host") {
+            cfg.tags.push(SettingsPair { key: "host".to_string(), val: "${HOSTNAME}".to_string() });
+        }
+        Ok(Self{
+            cfg,
+            client: None,
+            buffer,
+            hostname,
+        })
+    }
+    pub async fn connect(&mut self) -> Result<()> {
+      match self.client {
+        Some(_) => Ok(()),
+        None =>  {
+            use influxdb2::Client;
+            let cfg = &self.cfg;
+            let client = Client::new(&cfg.url, &cfg.org, &cfg.token);
+            println!("connected to InfluxDB at {0:?}", self.cfg.url);
+            self.client = Some(client);
+            Ok(())
+          },
+      }
+    }
+    pub fn disconnect(&mut self) {
+        self.client = None;
+    }
+    pub fn bucket(&self) -> &str {
+        &self.cfg.bucket
+    }
+    /// Runs an arbitrary Flux query, reconnecting first if necessary. The
+    /// query text names its own bucket (see `bucket()`). Used by the `doctor`
+    /// subcommand to read back what was written.
+    pub async fn query<T: influxdb2::FromMap>(&mut self, flux: String) -> Result<Vec<T>> {
+        self.connect().await?;
+        let client = self.client.as_ref().unwrap();
+        use influxdb2::models::Query;
+        Ok(client.query::<T>(Some(Query::new(flux))).await?)
+    }
+    /// Write a batch of points, coming from any `Source`, tagging each with
+    /// the configured Influx tags. If the write to InfluxDB fails, the points
+    /// are pushed into the retry buffer rather than dropped; points that
+    /// cannot be encoded return an error without being buffered.
+    pub async fn write_points(&mut self, points: &[DataPoint]) -> Result<()> {
+        // Automatically reconnect if we're not connected
+        self.connect().await?;
+        self.flush_buffer().await;
+        // connect either created client, or errored out
+        // so unwrap can't fail here
+        let influx_points = build_influx_points(points, &self.cfg, &self.hostname)?;
+        let client = self.client.as_ref().unwrap();
+        match client.write(&self.cfg.bucket, futures::stream::iter(influx_points)).await {
+            Ok(()) => Ok(()),
+            Err(error) => {
+                for point in points {
+                    self.buffer.push(point.clone());
+                }
+                Err(error.into())
+            },
+        }
+    }
+
+    /// Replay buffered points in FIFO batches before writing anything new,
+    /// so a reconnect catches up on everything missed during the outage.
+    async fn flush_buffer(&mut self) {
+        if self.client.is_none() || self.buffer.len() == 0 {
+            return;
+        }
+        loop {
+            let batch = self.buffer.drain_batch(FLUSH_BATCH_SIZE);
+            if batch.is_empty() {
+                break;
+            }
+            let influx_points = match build_influx_points(&batch, &self.cfg, &self.hostname) {
+                Ok(points) => points,
+                Err(error) => {
+                    println!("Error encoding buffered points, dropping batch: {error:?}");
+                    break;
+                },
+            };
+            let client = self.client.as_ref().unwrap();
+            match client.write(&self.cfg.bucket, futures::stream::iter(influx_points)).await {
+                Ok(()) => println!("Flushed {} buffered point(s) from retry buffer", batch.len()),
+                Err(error) => {
+                    println!("Error flushing buffered points, re-queuing: {error:?}");
+                    self.buffer.requeue_front(batch);
+                    break;
+                },
+            }
+        }
+    }
+}
+
+fn build_influx_points(points: &[DataPoint], cfg: &InfluxSettings, hostname: &str) -> Result<Vec<influxdb2::models::DataPoint>> {
+    use influxdb2::models::DataPoint as InfluxPoint;
+    points.iter().map(|point| {
+        let builder = point.fields.iter().fold(
+            InfluxPoint::builder(point.measurement.clone()),
+            |b, (name, value)| match value {
+                FieldValue::Int(v) => b.field(name.clone(), *v),
+                FieldValue::Float(v) => b.field(name.clone(), *v),
+            });
+        let builder = point.tags.iter().fold(
+            builder,
+            |b, (key, val)| b.tag(key.clone(), val.clone()));
+        let builder = cfg.tags.iter().fold(
+            builder,
+            |b, tag| b.tag(tag.key.clone(), expand_tag_value(&tag.val, point, hostname)));
+        builder.timestamp(point.timestamp).build()
+    }).collect::<core::result::Result<Vec<_>, _>>().map_err(|error| error.into())
+}
+
+/// Expands `${HOSTNAME}`, `${SERIALNO}` (from the point's own `serialno`
+/// tag, if any) and `${ENV_VAR}` references in a configured tag value, so
+/// the same TOML can be deployed to multiple machines/sensors unedited.
+fn expand_tag_value(template: &str, point: &DataPoint, hostname: &str) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        let name = &rest[start + 2..start + end];
+        let value = match name {
+            "HOSTNAME" => hostname.to_string(),
+            "SERIALNO" => point.tags.iter()
+                .find(|(key, _)| key == "serialno")
+                .map(|(_, val)| val.clone())
+                .unwrap_or_default(),
+            other => std::env::var(other).unwrap_or_default(),
+        };
+        result.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
@@ -1,199 +1,134 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
 use serde::Deserialize;
-use tokio;
+use tokio::sync::Mutex;
 
-type Result<T> = core::result::Result<T, Box<dyn std::error::Error>>;
+mod aqi;
+mod backoff;
+mod buffer;
+mod influx;
+mod source;
+mod sources;
 
-// Names are based on JSON format
-// We need to parse out fields we just toss
-#[allow(non_snake_case, dead_code)]
-#[derive(Deserialize,Debug)]
-pub struct AirGradientData {
-    wifi: i32,
-    serialno: String,
-    rco2: i32,
-    pm01: i32,
-    pm02: i32,
-    pm10: i32,
-    pm003Count: i32,
-    atmp: f32,
-    rhum: i32,
-    atmpCompensated: f32,
-    rhumCompensated: i32,
-    tvocIndex: i32,
-    tvocRaw: i32,
-    noxIndex: i32,
-    noxRaw: i32,
-    boot: i32,
-    bootCount: i32,
-    ledMode: String,
-    firmware: String,
-    model: String,
-}
+use backoff::Backoff;
+use influx::{Influx, InfluxSettings};
+use source::{Source, SourceConfig};
+use sources::airgradient;
 
-//***************************** Influx **********************************
+type Result<T> = core::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
-// This is a workaround.
-// ideally we'd just use a BTreeMap<String,String>, but
-// the config crate case squashes keys. Doing it this way
-// makes the tag a value, rather than a key.
-#[derive(Debug, Deserialize, Clone)]
-struct SettingsPair {
-    key: String,
-    val: String,
+#[derive(Debug, Deserialize)]
+struct Settings {
+    sources: BTreeMap<String, SourceConfig>,
+    influxdb: InfluxSettings,
 }
 
-#[derive(Debug, Deserialize, Clone)]
-pub struct InfluxSettings {
-    token: String,
-    bucket: String,
-    org: String,
-    url: String,
-    tags: Vec<SettingsPair>,
-}
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(300);
 
-pub struct Influx {
-    cfg: InfluxSettings,
-    client: Option<influxdb2::Client>,
-}
-
-impl Influx {
-    pub fn new(cfg: &InfluxSettings) -> Self {
-        Self{
-            cfg: cfg.clone(),
-            client: None,
+async fn run_source(mut source: Box<dyn Source>, influx: Arc<Mutex<Influx>>) {
+    // Poll failures and write failures back off independently, so a source
+    // that can't reach its sensor doesn't also throttle Influx writes (and
+    // vice versa).
+    let mut poll_backoff = Backoff::new(BACKOFF_BASE, BACKOFF_MAX);
+    let mut write_backoff = Backoff::new(BACKOFF_BASE, BACKOFF_MAX);
+    let mut interval = tokio::time::interval(source.poll_interval());
+    loop {
+        interval.tick().await;
+        let points = match source.poll().await {
+            Ok(points) => {
+                poll_backoff.reset();
+                points
+            },
+            Err(error) => {
+                println!("Error polling source {:?}: {error:?}", source.name());
+                tokio::time::sleep(poll_backoff.next_delay()).await;
+                // The interval's next deadline is now in the past; without a
+                // reset it fires once per missed tick, bursting polls right
+                // when the source is recovering.
+                interval.reset();
+                continue;
+            },
+        };
+        let mut influx = influx.lock().await;
+        match influx.write_points(&points).await {
+            Ok(()) => write_backoff.reset(),
+            Err(error) => {
+                println!("Error writing points for source {:?}: {error:?}", source.name());
+                influx.disconnect();
+                drop(influx);
+                tokio::time::sleep(write_backoff.next_delay()).await;
+                interval.reset();
+            },
         }
     }
-    pub async fn connect(&mut self) -> Result<()> {
-      match self.client {
-        Some(_) => Ok(()),
-        None =>  {
-            use influxdb2::Client;
-            let cfg = &self.cfg;
-            let client = Client::new(&cfg.url, &cfg.org, &cfg.token);
-            println!("connected to InfluxDB at {0:?}", self.cfg.url);
-            self.client = Some(client);
-            Ok(())
-          },
-      }
-    }
-    pub fn disconnect(&mut self) {
-        self.client = None;
-    }
-    //pub async fn write_point(&mut self, data: &Vec<RegData>, names: &Vec<String>) -> Result<()> {
-    pub async fn write_point(&mut self, data: &AirGradientData, aqi: u32) -> Result<()> {
-        // Automatically reconnect if we're not connected
-        self.connect().await?;
-        // connect either created client, or errored out
-        // so unwrap can't fail here
-        let client = self.client.as_mut().unwrap();
-        // Build up the list of points
-        use influxdb2::models::DataPoint;
-        let timestamp = chrono::Utc::now().timestamp_nanos_opt().unwrap();
-        let cfg = &self.cfg;
-        // Build our point
-        let point: DataPoint = cfg.tags.iter().fold(
-            DataPoint::builder("airgradient")
-               .field("rco2", data.rco2 as i64)
-               .field("pm01", data.pm01 as i64)
-               .field("pm02", data.pm02 as i64)
-               .field("pm10", data.pm10 as i64)
-               .field("pm003Count", data.pm003Count as i64)
-               .field("temp", data.atmpCompensated as f64)
-               .field("humidity", data.rhumCompensated as i64)
-               .field("tvoc", data.tvocRaw as i64)
-               .field("tvocIndex", data.tvocIndex as i64)
-               .field("nox", data.noxRaw as i64)
-               .field("noxIndex", data.noxIndex as i64)
-               .field("aqi", aqi as i64)
-               .tag("firmware", &data.firmware)
-               .tag("model", &data.model)
-               .tag("serialno", &data.serialno)
-               .timestamp(timestamp),
-               |p, tag| p.tag(&tag.key, &tag.val)).build()?;
-         client.write(&self.cfg.bucket, futures::stream::iter([point])).await?;
-         Ok(())
-    }
 }
 
-
-
-#[derive(Debug, Deserialize)]
-struct AirGradientSettings {
-	url: String,
-  delaysecs: u64,
+/// Runs the `query`/`doctor` subcommand: reads back the most recent sample
+/// per sensor so operators can confirm the write path is actually landing
+/// data, without opening the InfluxDB UI.
+async fn run_doctor(settings: Settings) -> Result<()> {
+    let mut influx = Influx::new(&settings.influxdb)?;
+    let readings = airgradient::doctor(&mut influx).await?;
+    if readings.is_empty() {
+        println!("No airgradient samples found in the last 7 days");
+    }
+    for reading in readings {
+        println!(
+            "{}: last seen {}, aqi={}, pm02={}",
+            reading.serialno, reading.time, reading.aqi, reading.pm02,
+        );
+    }
+    Ok(())
 }
 
-#[derive(Debug, Deserialize)]
-struct Settings {
-	  airgradient: AirGradientSettings,
-    influxdb: InfluxSettings,
-}
+async fn run_daemon(settings: Settings) -> Result<()> {
+    // connect to influx
+    let mut influx = Influx::new(&settings.influxdb)?;
+    influx.connect().await?;
+    let influx = Arc::new(Mutex::new(influx));
 
-// algo taken from https://en.wikipedia.org/wiki/Air_quality_index#United_States
-fn compute_one_aqi(datum: f64, vector: [f64; 7]) -> u32 {
-    const AQI: [f64; 7] = [0.0, 50.0, 100.0, 150.0, 200.0, 300.0, 500.0];
-    let mut i = 0;
-    while i <= 6 && datum >= vector[i] {
-        i = i + 1;
+    let mut handles = Vec::new();
+    for (name, cfg) in settings.sources {
+        if !cfg.enabled() {
+            println!("Source {name:?} is disabled, skipping");
+            continue;
+        }
+        let source = cfg.build(name)?;
+        let influx = influx.clone();
+        handles.push(tokio::spawn(run_source(source, influx)));
     }
-    return (((AQI[i] - AQI[i-1]) / 
-    (vector[i] - vector[i-1])) * (datum - vector[i-1]) + AQI[i-1]) as u32;
-}
 
-fn compute_aqi(data: &AirGradientData) -> u32 {
-    const PM02: [f64; 7] = [0.0, 9.0, 35.4, 55.4, 125.4, 225.4, 325.4];
-    const PM10: [f64; 7] = [0.0, 54.0, 154.0, 254.0, 354.0, 424.0, 604.0];
-    //const NOX: [f64; 7] = [0.0, 53.0, 100.0, 360.0, 649.0, 1249.0, 2049.0];
-    let mut v: u32 = 0;
-    use std::cmp;
-    v = cmp::max(v, compute_one_aqi(data.pm02 as f64, PM02)); 
-    v = cmp::max(v, compute_one_aqi(data.pm10 as f64, PM10)); 
-    // noxRaw isn't the right unit
-    //v = cmp::max(v, compute_one_aqi(data.noxRaw as f64, NOX)); 
-    return v;
-}
-
-async fn do_stuff(influx: &mut Influx, request_url: &str) -> Result<()> {
-    let data = reqwest::get(request_url).await?.json::<AirGradientData>().await?;
-    let aqi = compute_aqi(&data);
-    influx.write_point(&data, aqi).await?;
-    return Ok(());
+    println!("Starting");
+    for handle in handles {
+        handle.await?;
+    }
+    Ok(())
 }
 
-
 #[tokio::main]
 async fn main() -> Result<()> {
-    use std::time::Duration;
-		// Read config
+    // Read config. The first argument is either a subcommand
+    // ("query"/"doctor") or, for backwards compatibility, the config path.
     use std::env;
     let args: Vec<String> = env::args().collect();
-    let cfgpath =
-        if args.len() < 2 {
-            "/etc/airgradient_monitor.toml"
-        } else {
-            &args[1]
-        };
+    let (doctor, cfgpath) = match args.get(1).map(String::as_str) {
+        Some("query") | Some("doctor") => (true, args.get(2).map(String::as_str).unwrap_or("/etc/airgradient_monitor.toml")),
+        Some(path) => (false, path),
+        None => (false, "/etc/airgradient_monitor.toml"),
+    };
     println!("Reading config file {cfgpath:?}");
-		let cfg = config::Config::builder()
-		.add_source(config::File::new(cfgpath, config::FileFormat::Toml))
-		.build()?;
+    let cfg = config::Config::builder()
+    .add_source(config::File::new(cfgpath, config::FileFormat::Toml))
+    .build()?;
     let settings : Settings = cfg.try_deserialize()?;
     println!("Read settings {settings:?}");
-    // connect to influx
-    let mut influx = Influx::new(&settings.influxdb);
-    influx.connect().await?;
-    let request_url = settings.airgradient.url + "/measures/current";
-    let mut interval = tokio::time::interval(Duration::from_secs(settings.airgradient.delaysecs));
-    println!("Starting");
-    loop {
-        match do_stuff(&mut influx, &request_url).await {
-            Ok(()) => (),
-            Err(error) => {
-                println!("Error {error:?}");
-                // ignore errors here
-                influx.disconnect();
-            },
-        }
-        interval.tick().await;
-    };
+
+    if doctor {
+        run_doctor(settings).await
+    } else {
+        run_daemon(settings).await
+    }
 }
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::sources::airgradient::{AirGradient, AirGradientSettings};
+
+type Result<T> = core::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// A single value written into a `DataPoint`'s field set.
+#[derive(Debug, Clone)]
+pub enum FieldValue {
+    Int(i64),
+    Float(f64),
+}
+
+/// A measurement produced by a `Source`, independent of any particular
+/// sensor's wire format. `Influx::write_points` turns these into
+/// `influxdb2` points, adding its own configured tags.
+#[derive(Debug, Clone)]
+pub struct DataPoint {
+    pub measurement: String,
+    pub tags: Vec<(String, String)>,
+    pub fields: Vec<(String, FieldValue)>,
+    pub timestamp: i64,
+}
+
+/// Something that can be polled on its own cadence to produce data points.
+/// `AirGradient` is the first implementor; other sensor types can be added
+/// without touching the scheduling or Influx-writing code.
+#[async_trait]
+pub trait Source: Send + Sync {
+    /// Name of this source instance, as configured (e.g. the `[sources.<name>]` key).
+    fn name(&self) -> &str;
+    /// How often this source should be polled.
+    fn poll_interval(&self) -> Duration;
+    /// Fetch current data from the underlying sensor/service.
+    async fn poll(&mut self) -> Result<Vec<DataPoint>>;
+}
+
+/// A `[sources.<name>]` table, tagged by `type`, so a single daemon can
+/// drive several sensor types (not just several AirGradient instances)
+/// without `main` knowing about each one.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SourceConfig {
+    Airgradient(AirGradientSettings),
+}
+
+impl SourceConfig {
+    pub fn enabled(&self) -> bool {
+        match self {
+            SourceConfig::Airgradient(cfg) => cfg.enabled,
+        }
+    }
+
+    /// Builds the `Source` this config describes.
+    pub fn build(self, name: String) -> Result<Box<dyn Source>> {
+        match self {
+            SourceConfig::Airgradient(cfg) => Ok(Box::new(AirGradient::new(name, cfg)?)),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use influxdb2::FromDataPoint;
+use serde::Deserialize;
+
+use crate::aqi::{compute_aqi, NowCast};
+use crate::influx::Influx;
+use crate::source::{DataPoint, FieldValue, Source};
+
+type Result<T> = core::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+// Names are based on JSON format
+// We need to parse out fields we just toss
+#[allow(non_snake_case, dead_code)]
+#[derive(Deserialize,Debug)]
+pub struct AirGradientData {
+    wifi: i32,
+    serialno: String,
+    rco2: i32,
+    pm01: i32,
+    pm02: i32,
+    pm10: i32,
+    pm003Count: i32,
+    atmp: f32,
+    rhum: i32,
+    atmpCompensated: f32,
+    rhumCompensated: i32,
+    tvocIndex: i32,
+    tvocRaw: i32,
+    noxIndex: i32,
+    noxRaw: i32,
+    boot: i32,
+    bootCount: i32,
+    ledMode: String,
+    firmware: String,
+    model: String,
+}
+
+fn default_enabled() -> bool { true }
+fn default_timeout_secs() -> u64 { 10 }
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AirGradientSettings {
+    pub url: String,
+    pub delaysecs: u64,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub measurement_prefix: String,
+    /// How long to wait for the sensor to respond before treating the poll
+    /// as failed.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Feed a NowCast-weighted concentration into the AQI breakpoint tables
+    /// instead of the instantaneous reading, smoothing noisy sub-hourly
+    /// polling the same way AirNow does.
+    #[serde(default)]
+    pub nowcast: bool,
+}
+
+/// Polls a single AirGradient sensor's `/measures/current` endpoint.
+pub struct AirGradient {
+    name: String,
+    cfg: AirGradientSettings,
+    request_url: String,
+    client: reqwest::Client,
+    pm02_nowcast: NowCast,
+    pm10_nowcast: NowCast,
+}
+
+impl AirGradient {
+    pub fn new(name: String, cfg: AirGradientSettings) -> Result<Self> {
+        let request_url = format!("{}/measures/current", cfg.url);
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(cfg.timeout_secs))
+            .build()?;
+        Ok(Self {
+            name,
+            cfg,
+            request_url,
+            client,
+            pm02_nowcast: NowCast::new(),
+            pm10_nowcast: NowCast::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl Source for AirGradient {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.cfg.delaysecs)
+    }
+
+    async fn poll(&mut self) -> Result<Vec<DataPoint>> {
+        let data = self.client.get(&self.request_url).send().await?.json::<AirGradientData>().await?;
+        let (pm02, pm10) = if self.cfg.nowcast {
+            self.pm02_nowcast.record(data.pm02 as f64);
+            self.pm10_nowcast.record(data.pm10 as f64);
+            (self.pm02_nowcast.value(data.pm02 as f64), self.pm10_nowcast.value(data.pm10 as f64))
+        } else {
+            (data.pm02 as f64, data.pm10 as f64)
+        };
+        let aqi = compute_aqi(pm02, pm10);
+        let measurement = format!("{}airgradient", self.cfg.measurement_prefix);
+        let timestamp = chrono::Utc::now().timestamp_nanos_opt().unwrap();
+        let point = DataPoint {
+            measurement,
+            timestamp,
+            tags: vec![
+                ("firmware".to_string(), data.firmware.clone()),
+                ("model".to_string(), data.model.clone()),
+                ("serialno".to_string(), data.serialno.clone()),
+            ],
+            fields: vec![
+                ("rco2".to_string(), FieldValue::Int(data.rco2 as i64)),
+                ("pm01".to_string(), FieldValue::Int(data.pm01 as i64)),
+                ("pm02".to_string(), FieldValue::Int(data.pm02 as i64)),
+                ("pm10".to_string(), FieldValue::Int(data.pm10 as i64)),
+                ("pm003Count".to_string(), FieldValue::Int(data.pm003Count as i64)),
+                ("temp".to_string(), FieldValue::Float(data.atmpCompensated as f64)),
+                ("humidity".to_string(), FieldValue::Int(data.rhumCompensated as i64)),
+                ("tvoc".to_string(), FieldValue::Int(data.tvocRaw as i64)),
+                ("tvocIndex".to_string(), FieldValue::Int(data.tvocIndex as i64)),
+                ("nox".to_string(), FieldValue::Int(data.noxRaw as i64)),
+                ("noxIndex".to_string(), FieldValue::Int(data.noxIndex as i64)),
+                ("aqi".to_string(), FieldValue::Int(aqi as i64)),
+            ],
+        };
+        Ok(vec![point])
+    }
+}
+
+/// Mirrors the fields written for each `airgradient` point, so we can read
+/// the most recent sample per sensor back out of InfluxDB. `time` has to be
+/// `DateTime<FixedOffset>`, not `DateTime<Utc>`, since that's what the
+/// `FromDataPoint` derive assigns from a `Value::TimeRFC`.
+#[derive(Debug, Default, FromDataPoint)]
+pub struct AirGradientReading {
+    pub serialno: String,
+    pub aqi: i64,
+    pub pm02: i64,
+    pub time: DateTime<FixedOffset>,
+}
+
+/// Queries the most recent `airgradient` sample for every `serialno` seen
+/// in the last week, so operators can spot stale sensors without opening
+/// the InfluxDB UI. Matches on a `*airgradient` measurement suffix, not the
+/// bare name, since a source's `measurement_prefix` can rename it (e.g.
+/// `bedroom_airgradient`).
+pub async fn doctor(influx: &mut Influx) -> Result<Vec<AirGradientReading>> {
+    let flux = format!(
+        r#"from(bucket: "{bucket}")
+  |> range(start: -7d)
+  |> filter(fn: (r) => r._measurement =~ /airgradient$/)
+  |> filter(fn: (r) => r._field == "aqi" or r._field == "pm02")
+  |> group(columns: ["serialno", "_field"])
+  |> last()
+  |> group()
+  |> pivot(rowKey: ["_time", "serialno"], columnKey: ["_field"], valueColumn: "_value")"#,
+        bucket = influx.bucket(),
+    );
+    influx.query::<AirGradientReading>(flux).await
+}